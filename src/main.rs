@@ -1,7 +1,7 @@
 use adw::prelude::*;
 use anyhow::Error;
 use byte_slice_cast::*;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use gst::prelude::*;
 use gstreamer as gst;
 use gstreamer_app as gst_app;
@@ -9,7 +9,9 @@ use gstreamer_audio as gst_audio;
 use gtk::{gdk, gio, glib, pango};
 use gtk4 as gtk;
 use log::*;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::sync_channel;
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -27,15 +29,15 @@ struct Args {
     #[arg(short, long)]
     language: Option<String>,
 
-    /// audio length (ms)
+    /// maximum VAD segment length (ms), see --vad-max-length
     #[arg(short, long, default_value_t = 10000)]
     length: usize,
 
-    /// audio step size (ms)
+    /// how often (ms) the capture buffer is polled and run through the VAD
     #[arg(short, long, default_value_t = 2000)]
     step: u64,
 
-    /// audio to keep from previous step (ms)
+    /// pre-roll kept before a detected speech onset (ms), so it isn't clipped
     #[arg(short, long, default_value_t = 200)]
     keep: usize,
 
@@ -55,20 +57,227 @@ struct Args {
     #[arg(long, default_value = "pipewiresrc")]
     source: String,
 
+    /// transcribe a file instead of a live device (for reproducible benchmarks/tests)
+    #[arg(long, conflicts_with = "test_tone")]
+    input_file: Option<String>,
+
+    /// feed a synthetic audiotestsrc tone instead of a live device
+    #[arg(long)]
+    test_tone: bool,
+
+    /// measure and print the real-time factor at end of stream (with --input-file)
+    #[arg(long)]
+    bench: bool,
+
+    /// denoise the captured audio with RNNoise before it reaches whisper
+    #[arg(long)]
+    denoise: bool,
+
+    /// normalize loudness (EBU R128) before the audio reaches whisper
+    #[arg(long)]
+    normalize: bool,
+
+    /// integrated loudness target (LUFS) for --normalize
+    #[arg(long, default_value_t = -23.0)]
+    target_lufs: f64,
+
+    /// VAD speech threshold, as a multiple of the adaptive noise floor
+    #[arg(long, default_value_t = 2.5)]
+    vad_threshold: f32,
+
+    /// trailing silence (ms) before a VAD segment is finalized
+    #[arg(long, default_value_t = 600)]
+    vad_hang: u64,
+
+    /// maximum VAD segment length (ms) before it is force-finalized [default: length]
+    #[arg(long)]
+    vad_max_length: Option<usize>,
+
+    /// write a timestamped transcript of finalized segments to this file
+    #[arg(long)]
+    output: Option<String>,
+
+    /// format used for --output
+    #[arg(long, value_enum, default_value_t = OutputFormat::Srt)]
+    output_format: OutputFormat,
+
+    /// path to a TOML config file [default: $XDG_CONFIG_HOME/sasayaki/config.toml]
+    #[arg(long)]
+    config: Option<String>,
+
+    /// serve live captions over WebSocket at this address (e.g. 127.0.0.1:9090)
+    #[arg(long)]
+    serve: Option<String>,
+
     /// log verbosity (-v, -vv...)
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
 }
 
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    Srt,
+    Vtt,
+}
+
+/// On-disk counterpart of [`Args`]. Every field is optional: an unset field
+/// falls back to the matching `Args` default, while an explicit CLI flag
+/// always overrides whatever is saved here.
+///
+/// Only `width`/`height` are persisted as "window geometry" on close, not
+/// position: GTK4 dropped `gtk_window_get_position`/`move` and Wayland's
+/// xdg-shell protocol deliberately doesn't expose a toplevel's position to
+/// its own client, so there is nothing to read back here.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct Config {
+    model: Option<String>,
+    language: Option<String>,
+    length: Option<usize>,
+    step: Option<u64>,
+    keep: Option<usize>,
+    height: Option<i32>,
+    width: Option<i32>,
+    font_size: Option<i32>,
+    source: Option<String>,
+    denoise: Option<bool>,
+    normalize: Option<bool>,
+    target_lufs: Option<f64>,
+    vad_threshold: Option<f32>,
+    vad_hang: Option<u64>,
+    vad_max_length: Option<usize>,
+    output: Option<String>,
+    output_format: Option<OutputFormat>,
+}
+
+fn config_path(explicit: &Option<String>) -> PathBuf {
+    if let Some(path) = explicit {
+        return PathBuf::from(path);
+    }
+
+    let config_home = std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            PathBuf::from(std::env::var("HOME").expect("HOME is not set")).join(".config")
+        });
+    config_home.join("sasayaki").join("config.toml")
+}
+
+fn load_config(path: &Path) -> Config {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return Config::default(),
+    };
+    toml::from_str(&content).unwrap_or_else(|e| {
+        warn!("Failed to parse config {path:?}: {e}");
+        Config::default()
+    })
+}
+
+/// Fill in any `Args` field that wasn't explicitly passed on the command
+/// line with the matching value from `config`, if present.
+fn merge_config(args: &mut Args, matches: &clap::ArgMatches, config: &Config) {
+    let from_cli = |name: &str| {
+        matches!(
+            matches.value_source(name),
+            Some(clap::parser::ValueSource::CommandLine)
+        )
+    };
+
+    if !from_cli("model") {
+        if let Some(v) = &config.model {
+            args.model = v.clone();
+        }
+    }
+    if !from_cli("language") && config.language.is_some() {
+        args.language = config.language.clone();
+    }
+    if !from_cli("length") {
+        if let Some(v) = config.length {
+            args.length = v;
+        }
+    }
+    if !from_cli("step") {
+        if let Some(v) = config.step {
+            args.step = v;
+        }
+    }
+    if !from_cli("keep") {
+        if let Some(v) = config.keep {
+            args.keep = v;
+        }
+    }
+    if !from_cli("height") {
+        if let Some(v) = config.height {
+            args.height = v;
+        }
+    }
+    if !from_cli("width") {
+        if let Some(v) = config.width {
+            args.width = v;
+        }
+    }
+    if !from_cli("font_size") {
+        if let Some(v) = config.font_size {
+            args.font_size = v;
+        }
+    }
+    if !from_cli("source") {
+        if let Some(v) = &config.source {
+            args.source = v.clone();
+        }
+    }
+    if !from_cli("denoise") {
+        if let Some(v) = config.denoise {
+            args.denoise = v;
+        }
+    }
+    if !from_cli("normalize") {
+        if let Some(v) = config.normalize {
+            args.normalize = v;
+        }
+    }
+    if !from_cli("target_lufs") {
+        if let Some(v) = config.target_lufs {
+            args.target_lufs = v;
+        }
+    }
+    if !from_cli("vad_threshold") {
+        if let Some(v) = config.vad_threshold {
+            args.vad_threshold = v;
+        }
+    }
+    if !from_cli("vad_hang") {
+        if let Some(v) = config.vad_hang {
+            args.vad_hang = v;
+        }
+    }
+    if !from_cli("vad_max_length") && config.vad_max_length.is_some() {
+        args.vad_max_length = config.vad_max_length;
+    }
+    if !from_cli("output") && config.output.is_some() {
+        args.output = config.output.clone();
+    }
+    if !from_cli("output_format") {
+        if let Some(v) = config.output_format {
+            args.output_format = v;
+        }
+    }
+}
+
 fn create_pipeline(
     source: &str,
+    input_file: &Option<String>,
+    test_tone: bool,
+    denoise: bool,
+    normalize: bool,
+    target_lufs: f64,
     buf: Arc<Mutex<VecDeque<f32>>>,
     buf_size: usize,
 ) -> Result<gst::Pipeline, Error> {
     gst::init()?;
 
     let pipeline = gst::Pipeline::default();
-    let src = gst::ElementFactory::make(source).build()?;
     let appsink = gst_app::AppSink::builder()
         .caps(
             &gst_audio::AudioCapsBuilder::new_interleaved()
@@ -78,10 +287,95 @@ fn create_pipeline(
                 .rate(16000)
                 .build(),
         )
+        // a live source already paces itself against the device clock, but
+        // filesrc!decodebin has no such limit and will decode a whole file
+        // as fast as disk I/O allows; force the appsink to pace itself
+        // against the pipeline clock so the capture ring (sized for
+        // real-time capture) never has to drop unconsumed audio
+        .sync(true)
         .build();
 
-    pipeline.add_many(&[&src, appsink.upcast_ref()])?;
-    src.link(&appsink)?;
+    // decoded/tone/denoised/normalized audio never arrives pre-negotiated to
+    // F32/1ch/16000, so it needs converting before appsink; a bare live
+    // device without any stage enabled can link straight through
+    let needs_conversion = input_file.is_some() || test_tone || denoise || normalize;
+
+    let mut downstream: Vec<gst::Element> = Vec::new();
+    if needs_conversion {
+        downstream.push(gst::ElementFactory::make("audioconvert").build()?);
+        downstream.push(gst::ElementFactory::make("audioresample").build()?);
+    }
+    if denoise {
+        // audiornnoise has fixed 48 kHz F32 mono pads and doesn't resample
+        // its own output, so resample back down to 16 kHz before anything else
+        downstream.push(gst::ElementFactory::make("audiornnoise").build()?);
+        downstream.push(gst::ElementFactory::make("audioresample").build()?);
+    }
+    if normalize {
+        downstream.push(
+            gst::ElementFactory::make("audioloudnorm")
+                .property("loudness-target", target_lufs)
+                .build()?,
+        );
+        // audioloudnorm only operates on and emits F64LE, so convert back to
+        // F32 before it reaches the F32 capsfilter below
+        downstream.push(gst::ElementFactory::make("audioconvert").build()?);
+    }
+    if needs_conversion {
+        downstream.push(
+            gst::ElementFactory::make("capsfilter")
+                .property(
+                    "caps",
+                    &gst_audio::AudioCapsBuilder::new_interleaved()
+                        .format(gst_audio::AUDIO_FORMAT_F32)
+                        .channels(1)
+                        .rate(16000)
+                        .build(),
+                )
+                .build()?,
+        );
+    }
+    downstream.push(appsink.clone().upcast());
+
+    if let Some(path) = input_file {
+        // decodebin only gets its src pad once it has sniffed the file, so
+        // the rest of the chain is linked statically and hooked up to it
+        // from a "pad-added" callback instead of `link_many` end-to-end
+        let filesrc = gst::ElementFactory::make("filesrc")
+            .property("location", path)
+            .build()?;
+        let decodebin = gst::ElementFactory::make("decodebin").build()?;
+
+        pipeline.add_many(&[&filesrc, &decodebin])?;
+        pipeline.add_many(&downstream.iter().collect::<Vec<_>>())?;
+        filesrc.link(&decodebin)?;
+        gst::Element::link_many(&downstream.iter().collect::<Vec<_>>())?;
+
+        let first_sink = downstream[0].clone();
+        decodebin.connect_pad_added(move |_, src_pad| {
+            let Some(sink_pad) = first_sink.static_pad("sink") else {
+                return;
+            };
+            if !sink_pad.is_linked() {
+                if let Err(e) = src_pad.link(&sink_pad) {
+                    error!("Failed to link decodebin output: {e:?}");
+                }
+            }
+        });
+    } else {
+        let src = if test_tone {
+            gst::ElementFactory::make("audiotestsrc")
+                .property("is-live", true)
+                .build()?
+        } else {
+            gst::ElementFactory::make(source).build()?
+        };
+
+        let mut chain = vec![src];
+        chain.extend(downstream);
+        pipeline.add_many(&chain.iter().collect::<Vec<_>>())?;
+        gst::Element::link_many(&chain.iter().collect::<Vec<_>>())?;
+    }
 
     appsink.set_callbacks(
         gst_app::AppSinkCallbacks::builder()
@@ -131,11 +425,29 @@ fn create_pipeline(
     Ok(pipeline)
 }
 
+/// A transcript cue with absolute start/end times (seconds since capture
+/// started), ready to be written out as SRT or WebVTT.
+struct Cue {
+    start_secs: f64,
+    end_secs: f64,
+    text: String,
+}
+
+/// Accumulated `--bench` measurements: audio duration transcribed versus the
+/// wall-clock time whisper spent on it, printed as a real-time factor at EOS.
+#[derive(Default)]
+struct BenchStats {
+    total_audio_secs: f64,
+    total_infer_secs: f64,
+}
+
 fn whisper(
     ctx: &mut WhisperContext,
     language: &Option<String>,
     audio_data: &[f32],
+    segment_start_secs: f64,
     result_sender: &glib::Sender<(String, bool)>,
+    cue_sender: Option<&std::sync::mpsc::Sender<Cue>>,
     fix: bool,
 ) {
     let mut params = FullParams::new(SamplingStrategy::Greedy { n_past: 0 });
@@ -148,8 +460,11 @@ fn whisper(
     params.set_print_special(false);
     params.set_print_progress(false);
     params.set_print_realtime(false);
-    params.set_print_timestamps(false);
-    params.set_single_segment(true);
+    // cue export needs per-segment timestamps, which requires multi-segment
+    // decoding; the live overlay only ever wants one flattened line of text
+    let timestamps = cue_sender.is_some() && fix;
+    params.set_print_timestamps(timestamps);
+    params.set_single_segment(!timestamps);
     // experimental
     //params.set_speed_up(true);
 
@@ -161,12 +476,263 @@ fn whisper(
     let mut result = String::new();
     for i in 0..num_segments {
         let segment = ctx.full_get_segment_text(i).expect("Failed to get segment");
+        if let Some(cue_sender) = cue_sender.filter(|_| timestamps) {
+            // whisper reports centisecond offsets relative to audio_data
+            let t0 = segment_start_secs + ctx.full_get_segment_t0(i) as f64 / 100.0;
+            let t1 = segment_start_secs + ctx.full_get_segment_t1(i) as f64 / 100.0;
+            let _ = cue_sender.send(Cue {
+                start_secs: t0,
+                end_secs: t1,
+                text: segment.clone(),
+            });
+        }
         result.push_str(&segment);
     }
     debug!("{result}");
     result_sender.send((result, fix)).unwrap();
 }
 
+fn format_srt_timestamp(secs: f64) -> String {
+    let millis = (secs * 1000.0).round() as i64;
+    let (h, rest) = (millis / 3_600_000, millis % 3_600_000);
+    let (m, rest) = (rest / 60_000, rest % 60_000);
+    let (s, ms) = (rest / 1000, rest % 1000);
+    format!("{h:02}:{m:02}:{s:02},{ms:03}")
+}
+
+fn format_vtt_timestamp(secs: f64) -> String {
+    let millis = (secs * 1000.0).round() as i64;
+    let (h, rest) = (millis / 3_600_000, millis % 3_600_000);
+    let (m, rest) = (rest / 60_000, rest % 60_000);
+    let (s, ms) = (rest / 1000, rest % 1000);
+    format!("{h:02}:{m:02}:{s:02}.{ms:03}")
+}
+
+fn write_cue(
+    output: &mut std::fs::File,
+    format: OutputFormat,
+    index: usize,
+    cue: &Cue,
+) -> std::io::Result<()> {
+    use std::io::Write;
+
+    match format {
+        OutputFormat::Srt => write!(
+            output,
+            "{}\n{} --> {}\n{}\n\n",
+            index,
+            format_srt_timestamp(cue.start_secs),
+            format_srt_timestamp(cue.end_secs),
+            cue.text.trim(),
+        ),
+        OutputFormat::Vtt => write!(
+            output,
+            "{} --> {}\n{}\n\n",
+            format_vtt_timestamp(cue.start_secs),
+            format_vtt_timestamp(cue.end_secs),
+            cue.text.trim(),
+        ),
+    }
+}
+
+/// width of a single energy-analysis frame (ms)
+const VAD_FRAME_MS: usize = 20;
+/// minimum segment length (ms), shorter spans are dropped as spurious blips
+const VAD_MIN_SEGMENT_MS: usize = 250;
+/// smoothing factor of the noise-floor exponential moving average
+const VAD_FLOOR_ALPHA: f32 = 0.05;
+
+/// Energy-based voice-activity segmenter.
+///
+/// Samples are fed in as they arrive from the capture buffer and are sliced
+/// into `VAD_FRAME_MS` frames. Each frame's RMS energy is compared against an
+/// adaptive noise floor (tracked while not speaking) to decide whether the
+/// frame is speech. A running segment is accumulated while in speech, primed
+/// with a pre-roll so onsets aren't clipped, and finalized after `hang_ms` of
+/// trailing silence or once `max_length_samples` is reached.
+struct Vad {
+    threshold_k: f32,
+    hang_ms: u64,
+    max_length_samples: usize,
+    min_segment_samples: usize,
+    frame_samples: usize,
+    noise_floor: f32,
+    in_speech: bool,
+    silence_ms: u64,
+    segment: Vec<f32>,
+    segment_start_samples: usize,
+    speech_samples: usize,
+    samples_seen: usize,
+    preroll: VecDeque<f32>,
+    preroll_size: usize,
+}
+
+/// A segment of audio along with the pipeline time (seconds since capture
+/// started) its first sample was recorded at.
+struct VadSegment {
+    audio: Vec<f32>,
+    start_secs: f64,
+}
+
+enum VadEvent {
+    Partial(VadSegment),
+    Final(VadSegment),
+}
+
+impl Vad {
+    fn new(threshold_k: f32, hang_ms: u64, max_length_ms: usize, preroll_ms: usize) -> Self {
+        Vad {
+            threshold_k,
+            hang_ms,
+            max_length_samples: 16000 * max_length_ms / 1000,
+            min_segment_samples: 16000 * VAD_MIN_SEGMENT_MS / 1000,
+            frame_samples: 16000 * VAD_FRAME_MS / 1000,
+            noise_floor: 0.0,
+            in_speech: false,
+            silence_ms: 0,
+            segment: Vec::new(),
+            segment_start_samples: 0,
+            speech_samples: 0,
+            samples_seen: 0,
+            preroll: VecDeque::new(),
+            preroll_size: 16000 * preroll_ms / 1000,
+        }
+    }
+
+    /// Feed newly captured samples through the segmenter, returning every
+    /// `Final` segment finalized during this call (a single drained chunk
+    /// can contain more than one, e.g. if whisper lags behind speech), plus
+    /// a trailing `Partial` snapshot if still in speech afterwards.
+    fn push_samples(&mut self, samples: &[f32]) -> Vec<VadEvent> {
+        let mut events = Vec::new();
+
+        for frame in samples.chunks(self.frame_samples) {
+            let rms = (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt();
+
+            if !self.in_speech {
+                self.noise_floor = if self.samples_seen == 0 {
+                    rms
+                } else {
+                    self.noise_floor * (1.0 - VAD_FLOOR_ALPHA) + rms * VAD_FLOOR_ALPHA
+                };
+            }
+
+            let is_speech = rms > self.noise_floor * self.threshold_k;
+
+            if is_speech {
+                if !self.in_speech {
+                    self.in_speech = true;
+                    self.segment.clear();
+                    self.segment.extend(self.preroll.iter());
+                    self.segment_start_samples = self.samples_seen - self.preroll.len();
+                    self.speech_samples = 0;
+                }
+                self.silence_ms = 0;
+                self.segment.extend_from_slice(frame);
+                self.speech_samples += frame.len();
+            } else if self.in_speech {
+                self.segment.extend_from_slice(frame);
+                self.silence_ms += VAD_FRAME_MS as u64;
+            }
+
+            self.preroll.extend(frame.iter().copied());
+            while self.preroll.len() > self.preroll_size {
+                self.preroll.pop_front();
+            }
+            self.samples_seen += frame.len();
+
+            if self.in_speech
+                && (self.silence_ms >= self.hang_ms
+                    || self.segment.len() >= self.max_length_samples)
+            {
+                self.in_speech = false;
+                self.silence_ms = 0;
+                if self.speech_samples >= self.min_segment_samples {
+                    events.push(VadEvent::Final(VadSegment {
+                        audio: std::mem::take(&mut self.segment),
+                        start_secs: self.segment_start_samples as f64 / 16000.0,
+                    }));
+                } else {
+                    self.segment.clear();
+                }
+            }
+        }
+
+        if self.in_speech {
+            events.push(VadEvent::Partial(VadSegment {
+                audio: self.segment.clone(),
+                start_secs: self.segment_start_samples as f64 / 16000.0,
+            }));
+        }
+
+        events
+    }
+
+    /// Force-finalize whatever segment is still accumulating, bypassing the
+    /// min-length guard: called on EOS, where there's no more audio coming
+    /// and dropping the tail would silently lose the final utterance.
+    fn force_finalize(&mut self) -> Option<VadSegment> {
+        if !self.in_speech || self.segment.is_empty() {
+            return None;
+        }
+        self.in_speech = false;
+        self.silence_ms = 0;
+        self.speech_samples = 0;
+        Some(VadSegment {
+            audio: std::mem::take(&mut self.segment),
+            start_secs: self.segment_start_samples as f64 / 16000.0,
+        })
+    }
+}
+
+/// Accepts WebSocket connections on `addr` and fans out every message sent
+/// to `tx` as-is (one JSON object per transcription update) to all connected
+/// clients, so OBS/browsers/etc. can render sasayaki's captions remotely.
+async fn run_caption_server(addr: String, tx: tokio::sync::broadcast::Sender<String>) {
+    use futures_util::SinkExt;
+
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to bind caption server to {addr}: {e}");
+            return;
+        }
+    };
+    info!("Serving live captions on ws://{addr}");
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("Failed to accept caption client: {e}");
+                continue;
+            }
+        };
+
+        let mut rx = tx.subscribe();
+        tokio::spawn(async move {
+            let ws = match tokio_tungstenite::accept_async(stream).await {
+                Ok(ws) => ws,
+                Err(e) => {
+                    error!("WebSocket handshake with {peer} failed: {e}");
+                    return;
+                }
+            };
+            let (mut write, _read) = futures_util::StreamExt::split(ws);
+
+            while let Ok(msg) = rx.recv().await {
+                if write
+                    .send(tokio_tungstenite::tungstenite::Message::Text(msg))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+}
+
 #[derive(Clone)]
 struct Window {
     window: adw::ApplicationWindow,
@@ -268,8 +834,21 @@ impl Window {
     }
 }
 
+/// What wakes up the audio-draining consumer thread: either a normal
+/// `--step`-interval poll, or an EOS-triggered request to force-finalize
+/// whatever segment the VAD is still holding before the process quits.
+enum Tick {
+    Data,
+    Flush(std::sync::mpsc::Sender<()>),
+}
+
 fn main() -> Result<(), Error> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).expect("Failed to parse arguments");
+    let config_path = config_path(&args.config);
+    let config = load_config(&config_path);
+    merge_config(&mut args, &matches, &config);
+
     stderrlog::new()
         .module(module_path!())
         .timestamp(stderrlog::Timestamp::Second)
@@ -279,22 +858,72 @@ fn main() -> Result<(), Error> {
 
     let main_loop = glib::MainLoop::new(None, false);
 
-    let audio_buf_size = 16000 * args.length / 1000;
-    let max_buf_size = audio_buf_size * 2;
+    let vad_max_length = args.vad_max_length.unwrap_or(args.length);
+    let max_buf_size = 16000 * vad_max_length / 1000 * 2;
     let buf = Arc::new(Mutex::new(VecDeque::new()));
-    let pipeline = create_pipeline(&args.source, buf.clone(), max_buf_size).unwrap();
+    let pipeline = create_pipeline(
+        &args.source,
+        &args.input_file,
+        args.test_tone,
+        args.denoise,
+        args.normalize,
+        args.target_lufs,
+        buf.clone(),
+        max_buf_size,
+    )
+    .unwrap();
     pipeline.set_state(gst::State::Playing).unwrap();
 
     let bus = pipeline
         .bus()
         .expect("Pipeline without bus. Shouldn't happen!");
 
+    let bench_stats = args.bench.then(|| Arc::new(Mutex::new(BenchStats::default())));
+
+    // created here (rather than further down, where the GTK app is wired up)
+    // so the bus watch below can quit it: we run via `app.run_with_args`, not
+    // `main_loop.run()`, so only `app.quit()` actually ends the process
+    let app = adw::Application::new(
+        Some(&format!("org.u7fa9.{}", env!("CARGO_PKG_NAME"))),
+        gio::ApplicationFlags::FLAGS_NONE,
+    );
+
+    // filled in once `connect_activate` spins up the consumer thread below;
+    // the bus watch reads it to ask that thread to flush the in-progress VAD
+    // segment on EOS before the process quits
+    let tick_sender_slot: Arc<Mutex<Option<std::sync::mpsc::SyncSender<Tick>>>> =
+        Arc::new(Mutex::new(None));
+
     {
         let main_loop = main_loop.clone();
+        let bench_stats = bench_stats.clone();
+        let app = app.clone();
+        let tick_sender_slot = tick_sender_slot.clone();
         bus.add_watch(move |_, msg| {
             use gst::MessageView;
             match msg.view() {
-                MessageView::Eos(..) => main_loop.quit(),
+                MessageView::Eos(..) => {
+                    let tick_sender = tick_sender_slot.lock().unwrap().clone();
+                    if let Some(tick_sender) = tick_sender {
+                        let (ack_sender, ack_receiver) = std::sync::mpsc::channel();
+                        if tick_sender.send(Tick::Flush(ack_sender)).is_ok() {
+                            let _ = ack_receiver.recv_timeout(Duration::from_secs(5));
+                        }
+                    }
+                    if let Some(stats) = &bench_stats {
+                        let stats = stats.lock().unwrap();
+                        if stats.total_audio_secs > 0.0 {
+                            info!(
+                                "bench: {:.2}s audio transcribed in {:.2}s ({:.3}x real-time)",
+                                stats.total_audio_secs,
+                                stats.total_infer_secs,
+                                stats.total_infer_secs / stats.total_audio_secs,
+                            );
+                        }
+                    }
+                    main_loop.quit();
+                    app.quit();
+                }
                 MessageView::Error(err) => {
                     error!(
                         "Erro from {:?}: {} ({:?})",
@@ -303,6 +932,7 @@ fn main() -> Result<(), Error> {
                         err.debug(),
                     );
                     main_loop.quit();
+                    app.quit();
                 }
                 _ => (),
             };
@@ -312,51 +942,170 @@ fn main() -> Result<(), Error> {
         .expect("Failed to add bus watch");
     }
 
-    let app = adw::Application::new(
-        Some(&format!("org.u7fa9.{}", env!("CARGO_PKG_NAME"))),
-        gio::ApplicationFlags::FLAGS_NONE,
-    );
+    let cue_sender = args.output.as_ref().map(|path| {
+        let mut output = std::fs::File::create(path)
+            .unwrap_or_else(|e| panic!("Failed to create output file {path}: {e}"));
+        if let OutputFormat::Vtt = args.output_format {
+            use std::io::Write;
+            writeln!(output, "WEBVTT\n").unwrap();
+        }
+
+        let (cue_sender, cue_receiver) = std::sync::mpsc::channel::<Cue>();
+        let output_format = args.output_format;
+        thread::spawn(move || {
+            let mut index = 1;
+            for cue in cue_receiver {
+                write_cue(&mut output, output_format, index, &cue).expect("Failed to write cue");
+                index += 1;
+            }
+        });
+        cue_sender
+    });
+
+    let caption_broadcast = args.serve.as_ref().map(|addr| {
+        let (tx, _rx) = tokio::sync::broadcast::channel::<String>(32);
+        let addr = addr.clone();
+        let server_tx = tx.clone();
+        thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new()
+                .expect("Failed to start caption server runtime");
+            rt.block_on(run_caption_server(addr, server_tx));
+        });
+        tx
+    });
+
     gio::resources_register_include!("styles.gresource").expect("Failed to register resources.");
     app.connect_activate(move |app| {
         let (result_sender, result_receiver) =
             glib::MainContext::channel(glib::source::PRIORITY_DEFAULT);
 
-        let (tick_sender, tick_receiver) = sync_channel::<()>(1);
+        let (tick_sender, tick_receiver) = sync_channel::<Tick>(1);
+        *tick_sender_slot.lock().unwrap() = Some(tick_sender.clone());
         let mut ctx = WhisperContext::new(&args.model)
             .expect(&format!("Failed to load model {}", &args.model));
         glib::timeout_add(Duration::from_millis(args.step), move || {
-            let _ = tick_sender.try_send(());
+            let _ = tick_sender.try_send(Tick::Data);
             glib::Continue(true)
         });
         let buf = buf.clone();
         let language = args.language.clone();
+        let vad_max_length = args.vad_max_length.unwrap_or(args.length);
+        let mut vad = Vad::new(args.vad_threshold, args.vad_hang, vad_max_length, args.keep);
+        let cue_sender = cue_sender.clone();
+        let bench_stats = bench_stats.clone();
         thread::spawn(move || {
-            let mut fix_next = false;
             loop {
-                tick_receiver.recv().unwrap();
-                let fix = fix_next;
-                let mut audio_data = Vec::new();
-                {
-                    let mut buf = buf.lock().unwrap();
-                    buf.make_contiguous().clone_into(&mut audio_data);
-                    if buf.len() >= audio_buf_size {
-                        buf.clear();
-                        let keep_size = 16000 * args.keep / 1000;
-                        buf.extend(&audio_data[(audio_data.len() - keep_size)..]);
-                        fix_next = true;
-                    } else {
-                        fix_next = false;
+                match tick_receiver.recv().unwrap() {
+                    Tick::Data => {
+                        let mut audio_data = Vec::new();
+                        {
+                            let mut buf = buf.lock().unwrap();
+                            buf.make_contiguous().clone_into(&mut audio_data);
+                            buf.clear();
+                        }
+                        if audio_data.is_empty() {
+                            continue;
+                        }
+                        for event in vad.push_samples(&audio_data) {
+                            match event {
+                                VadEvent::Final(segment) => {
+                                    let audio_secs = segment.audio.len() as f64 / 16000.0;
+                                    let infer_started = std::time::Instant::now();
+                                    whisper(
+                                        &mut ctx,
+                                        &language,
+                                        &segment.audio,
+                                        segment.start_secs,
+                                        &result_sender,
+                                        cue_sender.as_ref(),
+                                        true,
+                                    );
+                                    if let Some(stats) = &bench_stats {
+                                        let mut stats = stats.lock().unwrap();
+                                        stats.total_audio_secs += audio_secs;
+                                        stats.total_infer_secs +=
+                                            infer_started.elapsed().as_secs_f64();
+                                    }
+                                }
+                                VadEvent::Partial(segment) => {
+                                    whisper(
+                                        &mut ctx,
+                                        &language,
+                                        &segment.audio,
+                                        segment.start_secs,
+                                        &result_sender,
+                                        cue_sender.as_ref(),
+                                        false,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    Tick::Flush(ack) => {
+                        // the pipeline hit EOS with a segment still in
+                        // progress; finalize it now so it isn't dropped from
+                        // the overlay, the SRT/VTT output and the bench
+                        // totals, then signal the bus watch it's safe to quit
+                        if let Some(segment) = vad.force_finalize() {
+                            let audio_secs = segment.audio.len() as f64 / 16000.0;
+                            let infer_started = std::time::Instant::now();
+                            whisper(
+                                &mut ctx,
+                                &language,
+                                &segment.audio,
+                                segment.start_secs,
+                                &result_sender,
+                                cue_sender.as_ref(),
+                                true,
+                            );
+                            if let Some(stats) = &bench_stats {
+                                let mut stats = stats.lock().unwrap();
+                                stats.total_audio_secs += audio_secs;
+                                stats.total_infer_secs += infer_started.elapsed().as_secs_f64();
+                            }
+                        }
+                        let _ = ack.send(());
                     }
                 }
-                whisper(&mut ctx, &language, &audio_data, &result_sender, fix);
             }
         });
 
         let win = Window::new(&app, &args);
 
+        // only size is saved here; see the note on `Config` for why window
+        // position isn't part of this (GTK4/Wayland gives clients no way to
+        // read their own toplevel position back)
+        {
+            let mut saved_config = config.clone();
+            let config_path = config_path.clone();
+            let window = win.window.clone();
+            win.window.connect_close_request(move |_| {
+                saved_config.width = Some(window.width());
+                saved_config.height = Some(window.height());
+                if let Some(parent) = config_path.parent() {
+                    let _ = std::fs::create_dir_all(parent);
+                }
+                match toml::to_string_pretty(&saved_config) {
+                    Ok(contents) => {
+                        if let Err(e) = std::fs::write(&config_path, contents) {
+                            warn!("Failed to save window geometry to {config_path:?}: {e}");
+                        }
+                    }
+                    Err(e) => warn!("Failed to serialize config: {e}"),
+                }
+                gtk::Inhibit(false)
+            });
+        }
+
         result_receiver.attach(None, {
             let mut win = win.clone();
+            let caption_broadcast = caption_broadcast.clone();
             move |(text, fix)| {
+                if let Some(tx) = &caption_broadcast {
+                    let message = serde_json::json!({ "text": text, "final": fix }).to_string();
+                    let _ = tx.send(message);
+                }
+
                 if fix {
                     win.fix_label();
                 }
@@ -393,3 +1142,65 @@ fn main() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srt_timestamp_formats_hours_minutes_seconds_millis() {
+        assert_eq!(format_srt_timestamp(0.0), "00:00:00,000");
+        assert_eq!(format_srt_timestamp(1.5), "00:00:01,500");
+        assert_eq!(format_srt_timestamp(3661.025), "01:01:01,025");
+    }
+
+    #[test]
+    fn vtt_timestamp_formats_hours_minutes_seconds_millis() {
+        assert_eq!(format_vtt_timestamp(0.0), "00:00:00.000");
+        assert_eq!(format_vtt_timestamp(1.5), "00:00:01.500");
+        assert_eq!(format_vtt_timestamp(3661.025), "01:01:01.025");
+    }
+
+    fn tone(secs: f64, amplitude: f32) -> Vec<f32> {
+        let n = (16000.0 * secs) as usize;
+        (0..n)
+            .map(|i| amplitude * (i as f32 * 0.1).sin())
+            .collect()
+    }
+
+    fn silence(secs: f64) -> Vec<f32> {
+        vec![0.0; (16000.0 * secs) as usize]
+    }
+
+    #[test]
+    fn push_samples_finalizes_a_segment_after_enough_trailing_silence() {
+        let mut vad = Vad::new(2.0, 300, 10_000, 200);
+
+        // settle the noise floor against silence first
+        let events = vad.push_samples(&silence(0.5));
+        assert!(events.is_empty());
+
+        let mut events = vad.push_samples(&tone(1.0, 0.9));
+        events.extend(vad.push_samples(&silence(0.5)));
+
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, VadEvent::Final(_) | VadEvent::Partial(_))));
+        assert!(events.iter().any(|e| matches!(e, VadEvent::Final(_))));
+    }
+
+    #[test]
+    fn push_samples_drops_a_blip_shorter_than_the_minimum_segment_length() {
+        let mut vad = Vad::new(2.0, 300, 10_000, 200);
+
+        let events = vad.push_samples(&silence(0.5));
+        assert!(events.is_empty());
+
+        // a blip well under VAD_MIN_SEGMENT_MS, even padded with pre-roll,
+        // must not be emitted as a `Final` segment
+        let mut events = vad.push_samples(&tone(0.05, 0.9));
+        events.extend(vad.push_samples(&silence(0.5)));
+
+        assert!(!events.iter().any(|e| matches!(e, VadEvent::Final(_))));
+    }
+}